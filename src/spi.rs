@@ -2,7 +2,9 @@
 //!
 //! The spi bus acts as the master (generating the clock) and you need to handle the CS separately.
 //!
-//! The most significant bit is transmitted first & only 8-bit transfers are supported
+//! The most significant bit is transmitted first. 8- and 16-bit transfers
+//! are supported out of the box, and arbitrary 4-16 bit frame sizes via
+//! `Spi::into_data_size`
 //!
 //! # Example
 //! Echo incoming data in the next transfer
@@ -37,11 +39,21 @@
 //! });
 //! ```
 
+use core::cell::Cell;
+use core::future::Future;
 use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
 use core::{ops::Deref, ptr};
 
+use cortex_m::asm;
+use embedded_hal::digital::v2::OutputPin;
 pub use embedded_hal::spi::{Mode, Phase, Polarity};
 
+// The `embedded-hal` 1.0 crate, depended on as `eh1` since the 0.2 traits
+// above are still the primary API.
+use eh1::spi::{ErrorKind, ErrorType, SpiBus};
+
 // TODO Put this inside the macro
 // Currently that causes a compiler panic
 use crate::pac::SPI1;
@@ -62,6 +74,50 @@ pub struct EightBit;
 /// Typestate for 16-bit transfer size
 pub struct SixteenBit;
 
+fn frame_mask(bits: u8) -> u16 {
+    if bits >= 16 {
+        0xFFFF
+    } else {
+        (1u16 << bits) - 1
+    }
+}
+
+/// Result of [`Spi::into_data_size`]: frames of 8 bits or fewer are
+/// clocked through the 8-bit FIFO window, wider frames through the 16-bit
+/// window, matching whichever `FRXTH` setting was programmed.
+pub enum AnyDataSize<SPI, SCKPIN, MISOPIN, MOSIPIN> {
+    /// Custom frame of 8 bits or fewer, using the 8-bit `DR` access.
+    Eight(Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>),
+    /// Custom frame of 9 to 16 bits, using the 16-bit `DR` access.
+    Sixteen(Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit>),
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> AnyDataSize<SPI, SCKPIN, MISOPIN, MOSIPIN>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Send `word`, masked down to the low `bits` bits of the configured
+    /// custom frame size, through whichever FIFO width was configured.
+    pub fn send_n(&mut self, word: u16, bits: u8) {
+        let word = word & frame_mask(bits);
+        match self {
+            AnyDataSize::Eight(spi) => spi.send_u8(word as u8),
+            AnyDataSize::Sixteen(spi) => spi.send_u16(word),
+        }
+    }
+
+    /// Read back a word, masked down to the low `bits` bits of the
+    /// configured custom frame size, through whichever FIFO width was
+    /// configured.
+    pub fn read_n(&mut self, bits: u8) -> u16 {
+        let word = match self {
+            AnyDataSize::Eight(spi) => spi.read_u8() as u16,
+            AnyDataSize::Sixteen(spi) => spi.read_u16(),
+        };
+        word & frame_mask(bits)
+    }
+}
+
 /// SPI error
 #[non_exhaustive]
 #[derive(Debug)]
@@ -76,6 +132,16 @@ pub enum Error {
     IncompleteTransfer,
 }
 
+impl eh1::spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::Crc | Error::IncompleteTransfer => ErrorKind::Other,
+        }
+    }
+}
+
 /// SPI abstraction
 pub struct Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
     spi: SPI,
@@ -87,6 +153,26 @@ pub trait SckPin<SPI> {}
 pub trait MisoPin<SPI> {}
 pub trait MosiPin<SPI> {}
 
+/// Placeholder satisfying [`SckPin`] for buses that don't drive a clock pin.
+pub struct NoSck;
+/// Placeholder satisfying [`MisoPin`] for half-duplex/transmit-only buses
+/// that have no separate MISO line, e.g. a shared 3-wire data line.
+pub struct NoMiso;
+/// Placeholder satisfying [`MosiPin`] for half-duplex/receive-only buses
+/// that have no separate MOSI line, e.g. a shared 3-wire data line.
+pub struct NoMosi;
+
+impl SckPin<SPI1> for NoSck {}
+impl MisoPin<SPI1> for NoMiso {}
+impl MosiPin<SPI1> for NoMosi {}
+
+#[cfg(any(feature = "py32f030"))]
+impl SckPin<SPI2> for NoSck {}
+#[cfg(any(feature = "py32f030"))]
+impl MisoPin<SPI2> for NoMiso {}
+#[cfg(any(feature = "py32f030"))]
+impl MosiPin<SPI2> for NoMosi {}
+
 macro_rules! spi_pins {
     ($($SPI:ident => {
         sck => [$($sck:ty),+ $(,)*],
@@ -420,6 +506,40 @@ where
         }
     }
 
+    /// Configure an arbitrary data frame size from 4 to 16 bits.
+    ///
+    /// `DS` is programmed directly (the field encodes frame size as
+    /// `bits - 1`), and `FRXTH`/the FIFO access width follow the frame
+    /// size: frames of 8 bits or fewer get the 8-bit threshold and are clocked through the 8-bit
+    /// `DR` window, wider frames get the 16-bit threshold and window. Use
+    /// [`AnyDataSize::send_n`]/[`AnyDataSize::read_n`] to mask words down
+    /// to the configured width.
+    pub fn into_data_size(self, bits: u8) -> AnyDataSize<SPI, SCKPIN, MISOPIN, MOSIPIN> {
+        assert!((4..=16).contains(&bits), "frame size must be 4..=16 bits");
+
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi.cr2.write(|w| unsafe {
+            w.ds().bits(bits - 1).frxth().bit(bits <= 8).ssoe().clear_bit()
+        });
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+
+        let spi = self.spi;
+        let pins = self.pins;
+        if bits <= 8 {
+            AnyDataSize::Eight(Spi {
+                spi,
+                pins,
+                _width: PhantomData,
+            })
+        } else {
+            AnyDataSize::Sixteen(Spi {
+                spi,
+                pins,
+                _width: PhantomData,
+            })
+        }
+    }
+
     fn set_send_only(&mut self) {
         self.spi
             .cr1
@@ -432,6 +552,32 @@ where
             .modify(|_, w| w.bidimode().clear_bit().bidioe().clear_bit());
     }
 
+    /// Switch a half-duplex (3-wire) bus to drive the shared data line.
+    ///
+    /// Like the internal send-only path used by the `write` methods (and
+    /// unlike [`Spi::set_bidi`]), this sets `BIDIMODE` so the bus uses its
+    /// single data line, and sets `BIDIOE` (the direction bit) to transmit.
+    /// Call this before writing
+    /// on a bus built with [`NoMiso`] or [`NoMosi`].
+    pub fn set_bidi_output(&mut self) {
+        self.spi
+            .cr1
+            .modify(|_, w| w.bidimode().set_bit().bidioe().set_bit());
+    }
+
+    /// Switch a half-duplex (3-wire) bus to listen on the shared data line.
+    ///
+    /// See [`Spi::set_bidi_output`]; this clears `BIDIOE` so the shared data
+    /// line is turned around to receive. Every read and transfer method in
+    /// this module switches the bus back to two-line full-duplex mode
+    /// (`BIDIMODE` cleared) before it runs, so none of them receives over
+    /// the shared line.
+    pub fn set_bidi_input(&mut self) {
+        self.spi
+            .cr1
+            .modify(|_, w| w.bidimode().set_bit().bidioe().clear_bit());
+    }
+
     fn check_read(&mut self) -> nb::Result<(), Error> {
         let sr = self.spi.sr.read();
 
@@ -496,6 +642,113 @@ where
     pub fn release(self) -> (SPI, (SCKPIN, MISOPIN, MOSIPIN)) {
         (self.spi, self.pins)
     }
+
+    /// Wrap this `Spi` with a software-managed CS pin, driven low/high
+    /// around every transaction. Because master init uses `ssm`/`ssi`
+    /// software NSS, the bus otherwise leaves CS entirely up to the
+    /// caller; this lets several devices share the bus with per-device
+    /// clock speeds reprogrammed at the start of each transaction.
+    pub fn with_cs<CS>(self, cs: CS) -> SpiWithCs<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, CS>
+    where
+        CS: OutputPin,
+    {
+        SpiWithCs { spi: self, cs }
+    }
+
+    /// `CRCNEXT` must be set while the last data word is still shifting out
+    /// so the peripheral appends the computed CRC instead of another data
+    /// word; call this right after sending the final word when CRC is
+    /// enabled.
+    fn assert_crc_next(&mut self) {
+        if self.spi.cr1.read().crcen().bit_is_set() {
+            self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+        }
+    }
+
+    fn crc_enabled(&mut self) -> bool {
+        self.spi.cr1.read().crcen().bit_is_set()
+    }
+}
+
+/// Configuration for the peripheral's hardware CRC calculation.
+#[derive(Clone, Copy)]
+pub struct CrcConfig {
+    /// Polynomial programmed into `CRCPR`. Once CRC is enabled, the blocking
+    /// `Transfer`/`Write` impls and the `SpiBus` methods send the computed
+    /// CRC after the last data word, and the reading paths check the
+    /// received one. The DMA, interrupt-driven and [`SpiWithCs`] paths do
+    /// not handle CRC.
+    pub polynomial: u16,
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Enable hardware CRC generation/checking with an 8-bit CRC length,
+    /// matching the 8-bit data frame width.
+    pub fn with_crc(self, config: CrcConfig) -> Self {
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi
+            .crcpr
+            .write(|w| unsafe { w.crcpoly().bits(config.polynomial) });
+        self.spi
+            .cr1
+            .modify(|_, w| w.crcl().clear_bit().crcen().set_bit());
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+        self
+    }
+
+    /// When CRC is enabled, wait for the appended CRC byte, drain it out of
+    /// `DR` the same way every other FIFO byte is drained, and return
+    /// `Error::Crc` if the hardware flagged a mismatch in `CRCERR`.
+    /// `RXCRCR` is read but its value is not used.
+    fn check_crc(&mut self) -> Result<(), Error> {
+        if self.crc_enabled() {
+            nb::block!(self.check_read()).ok();
+            self.read_u8();
+            let _received_crc = self.spi.rxcrcr.read().bits();
+            if self.spi.sr.read().crcerr().bit_is_set() {
+                self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                return Err(Error::Crc);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Enable hardware CRC generation/checking with a 16-bit CRC length,
+    /// matching the 16-bit data frame width.
+    pub fn with_crc(self, config: CrcConfig) -> Self {
+        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi
+            .crcpr
+            .write(|w| unsafe { w.crcpoly().bits(config.polynomial) });
+        self.spi
+            .cr1
+            .modify(|_, w| w.crcl().set_bit().crcen().set_bit());
+        self.spi.cr1.modify(|_, w| w.spe().set_bit());
+        self
+    }
+
+    /// See the 8-bit `check_crc` above; drains the appended CRC half-word
+    /// out of `DR` instead of a byte.
+    fn check_crc(&mut self) -> Result<(), Error> {
+        if self.crc_enabled() {
+            nb::block!(self.check_read()).ok();
+            self.read_u16();
+            let _received_crc = self.spi.rxcrcr.read().bits();
+            if self.spi.sr.read().crcerr().bit_is_set() {
+                self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                return Err(Error::Crc);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<SPI, SCKPIN, MISOPIN, MOSIPIN> ::embedded_hal::blocking::spi::Transfer<u8>
@@ -504,27 +757,30 @@ where
     SPI: Deref<Target = SpiRegisterBlock>,
 {
     type Error = Error;
-    
+
     fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
         self.set_bidi(); // Ensure we're in the correct mode for bidirectional transfer
-    
+
         let mut read_pos = 0; // Keep track of the position we're reading from
         let mut write_pos = 0; // Keep track of the position we're writing to
-    
+
         while read_pos < words.len() {
             // Fill the transmit FIFO as much as possible
             while write_pos < words.len() && self.check_send().is_ok() {
                 self.send_u8(words[write_pos]);
                 write_pos += 1;
+                if write_pos == words.len() {
+                    self.assert_crc_next();
+                }
             }
-    
+
             // Read from the receive FIFO whenever possible
             while self.check_read().is_ok() && read_pos < write_pos {
                 words[read_pos] = self.read_u8();
                 read_pos += 1;
             }
         }
-    
+
         // After all writes are done, there might still be data left in the receive FIFO.
         // Drain the receive FIFO.
         while read_pos < words.len() {
@@ -536,7 +792,9 @@ where
                 return Err(Error::IncompleteTransfer);
             }
         }
-    
+
+        self.check_crc()?;
+
         // Check for overrun after the transfer is complete
         if self.spi.sr.read().ovr().bit_is_set() {
             Err(Error::Overrun)
@@ -564,7 +822,8 @@ where
         nb::block!(self.check_send())?;
 
         // We have a 32 bit buffer to work with, so let's fill it before checking the status
-        for word in words {
+        let len = words.len();
+        for (i, word) in words.iter().enumerate() {
             // Loop as long as our send buffer is full
             while bufcap == 0 {
                 bufcap = self.send_buffer_size();
@@ -572,6 +831,9 @@ where
 
             self.send_u8(*word);
             bufcap -= 1;
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
         }
 
         // Do one last status register check before continuing
@@ -591,13 +853,19 @@ where
         // We want to transfer bidirectionally, make sure we're in the correct mode
         self.set_bidi();
 
-        for word in words.iter_mut() {
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
             nb::block!(self.check_send())?;
             self.send_u16(*word);
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
             nb::block!(self.check_read())?;
             *word = self.read_u16();
         }
 
+        self.check_crc()?;
+
         Ok(words)
     }
 }
@@ -613,9 +881,13 @@ where
         // We only want to send, so we don't need to worry about the receive buffer overflowing
         self.set_send_only();
 
-        for word in words {
+        let len = words.len();
+        for (i, word) in words.iter().enumerate() {
             nb::block!(self.check_send())?;
             self.send_u16(*word);
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
         }
 
         // Do one last status register check before continuing
@@ -623,3 +895,770 @@ where
         Ok(())
     }
 }
+
+/// A DMA channel wired up to drive one side of an SPI peripheral.
+///
+/// This mirrors the handful of registers every DMA channel on these parts
+/// exposes (peripheral address, memory address, transfer count and the
+/// control register) without pulling in a full `dma` module, so `Spi` can
+/// stay generic over whichever channel the user has claimed.
+pub trait DmaChannel {
+    /// Program the fixed peripheral-side address (`CPAR`).
+    fn set_peripheral_address(&mut self, address: u32);
+    /// Program the memory-side start address (`CMAR`).
+    fn set_memory_address(&mut self, address: u32);
+    /// Program the number of words to transfer (`CNDTR`).
+    fn set_transfer_length(&mut self, len: u16);
+    /// Configure direction/word size/increment mode and enable the channel.
+    fn start(&mut self, memory_to_peripheral: bool, word_size: DmaWordSize);
+    /// True once this channel's transfer-complete flag is set.
+    fn is_complete(&self) -> bool;
+    /// Clear the transfer-complete flag and disable the channel.
+    fn finish(&mut self);
+}
+
+/// DMA peripheral/memory word size, matching the SPI data frame width.
+#[derive(Clone, Copy)]
+pub enum DmaWordSize {
+    /// One byte per word, used with [`EightBit`] frames.
+    Byte,
+    /// Two bytes per word, used with [`SixteenBit`] frames.
+    HalfWord,
+}
+
+/// Guard returned by the `*_dma` transfer methods.
+///
+/// The DMA channel(s) keep running in the background; [`SpiDmaTransfer::wait`]
+/// blocks until the transfer-complete flag(s) fire and hands the buffer
+/// back. Dropping the guard without calling `wait()` (an early return, a
+/// panic, or simply discarding it) blocks in `Drop` instead of leaving the
+/// channel armed over a buffer the caller could otherwise reuse or drop.
+#[must_use = "dropping this blocks until the DMA transfer completes; call `.wait()` to get the buffer back"]
+pub struct SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, TXC, RXC, BUF> {
+    spi: &'a mut Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>,
+    tx_channel: Option<TXC>,
+    rx_channel: Option<RXC>,
+    buffer: BUF,
+}
+
+impl<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, TXC, RXC, BUF>
+    SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, TXC, RXC, BUF>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+    TXC: DmaChannel,
+    RXC: DmaChannel,
+{
+    /// Block until the DMA channel(s) report completion, then disable them
+    /// and the SPI DMA-enable bits.
+    ///
+    /// DMA transfer-complete only means the last word has been pushed into
+    /// `DR`/the FIFO, not that it has finished shifting out on the wire, so
+    /// this also waits for `BSY` to clear before returning — otherwise a
+    /// caller that deasserts CS or reuses the TX buffer right after this
+    /// returns could cut off or corrupt the last word(s).
+    fn complete(&mut self) {
+        loop {
+            let tx_done = self.tx_channel.as_ref().map_or(true, |c| c.is_complete());
+            let rx_done = self.rx_channel.as_ref().map_or(true, |c| c.is_complete());
+            if tx_done && rx_done {
+                break;
+            }
+        }
+
+        while self.spi.spi.sr.read().bsy().bit_is_set() {}
+
+        if let Some(tx) = self.tx_channel.as_mut() {
+            tx.finish();
+        }
+        if let Some(rx) = self.rx_channel.as_mut() {
+            rx.finish();
+        }
+
+        self.spi
+            .spi
+            .cr2
+            .modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+    }
+
+    /// Block until the DMA channel(s) report completion, then return the
+    /// buffer and channel(s) so they can be reused.
+    pub fn wait(self) -> (BUF, Option<TXC>, Option<RXC>) {
+        // `Self` implements `Drop`, so its fields can't be moved out of
+        // `self` directly; run the same completion logic `Drop` would and
+        // then pull the fields out through `ManuallyDrop` so `drop` doesn't
+        // run (and block) a second time.
+        let mut this = core::mem::ManuallyDrop::new(self);
+        this.complete();
+
+        // SAFETY: `this` is wrapped in `ManuallyDrop`, so `this` itself is
+        // never dropped; `complete()` above already ran the one-time
+        // cleanup, and `this.spi` (a `&mut` reference, which has no drop
+        // glue) is simply left behind.
+        unsafe {
+            (
+                core::ptr::read(&this.buffer),
+                core::ptr::read(&this.tx_channel),
+                core::ptr::read(&this.rx_channel),
+            )
+        }
+    }
+}
+
+impl<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, TXC, RXC, BUF> Drop
+    for SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, TXC, RXC, BUF>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+    TXC: DmaChannel,
+    RXC: DmaChannel,
+{
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Send `buffer` over DMA on `tx_channel`, without touching the RX FIFO.
+    ///
+    /// Enables `TXDMAEN`, points the channel's peripheral address at `DR`
+    /// with fixed increment, and the memory address at `buffer` with
+    /// increment enabled, then starts the channel. Use the send-only path
+    /// (no RX channel needed) since nothing is read back.
+    pub fn write_dma<'a, TXC>(
+        &'a mut self,
+        buffer: &'a [u8],
+        mut tx_channel: TXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit, TXC, TXC, &'a [u8]>
+    where
+        TXC: DmaChannel,
+    {
+        self.set_send_only();
+        self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+        tx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        tx_channel.set_memory_address(buffer.as_ptr() as u32);
+        tx_channel.set_transfer_length(buffer.len() as u16);
+        tx_channel.start(true, DmaWordSize::Byte);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: Some(tx_channel),
+            rx_channel: None,
+            buffer,
+        }
+    }
+
+    /// Fill `buffer` over DMA on `rx_channel`, clocking out dummy bytes.
+    ///
+    /// Enables `RXDMAEN` and points the channel's peripheral address at
+    /// `DR` (fixed) and memory address at `buffer` (incrementing).
+    pub fn read_dma<'a, RXC>(
+        &'a mut self,
+        buffer: &'a mut [u8],
+        mut rx_channel: RXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit, RXC, RXC, &'a mut [u8]>
+    where
+        RXC: DmaChannel,
+    {
+        self.set_bidi();
+        self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+
+        rx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        rx_channel.set_memory_address(buffer.as_mut_ptr() as u32);
+        rx_channel.set_transfer_length(buffer.len() as u16);
+        rx_channel.start(false, DmaWordSize::Byte);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: None,
+            rx_channel: Some(rx_channel),
+            buffer,
+        }
+    }
+
+    /// Exchange `tx`/`rx` over DMA using two channels running concurrently.
+    ///
+    /// Both channels are armed before either is started so the RX channel
+    /// is always ready to drain the FIFO before the TX side can overflow
+    /// it.
+    pub fn transfer_dma<'a, TXC, RXC>(
+        &'a mut self,
+        tx: &'a [u8],
+        rx: &'a mut [u8],
+        mut tx_channel: TXC,
+        mut rx_channel: RXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit, TXC, RXC, (&'a [u8], &'a mut [u8])>
+    where
+        TXC: DmaChannel,
+        RXC: DmaChannel,
+    {
+        assert_eq!(tx.len(), rx.len());
+        self.set_bidi();
+
+        rx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        rx_channel.set_memory_address(rx.as_mut_ptr() as u32);
+        rx_channel.set_transfer_length(rx.len() as u16);
+
+        tx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        tx_channel.set_memory_address(tx.as_ptr() as u32);
+        tx_channel.set_transfer_length(tx.len() as u16);
+
+        self.spi
+            .cr2
+            .modify(|_, w| w.rxdmaen().set_bit().txdmaen().set_bit());
+
+        // Arm RX before TX so it is always draining the FIFO first.
+        rx_channel.start(false, DmaWordSize::Byte);
+        tx_channel.start(true, DmaWordSize::Byte);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: Some(tx_channel),
+            rx_channel: Some(rx_channel),
+            buffer: (tx, rx),
+        }
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Send `buffer` over DMA on `tx_channel` using 16-bit frames.
+    pub fn write_dma<'a, TXC>(
+        &'a mut self,
+        buffer: &'a [u16],
+        mut tx_channel: TXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit, TXC, TXC, &'a [u16]>
+    where
+        TXC: DmaChannel,
+    {
+        self.set_send_only();
+        self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+        tx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        tx_channel.set_memory_address(buffer.as_ptr() as u32);
+        tx_channel.set_transfer_length(buffer.len() as u16);
+        tx_channel.start(true, DmaWordSize::HalfWord);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: Some(tx_channel),
+            rx_channel: None,
+            buffer,
+        }
+    }
+
+    /// Fill `buffer` over DMA on `rx_channel` using 16-bit frames, clocking
+    /// out dummy half-words.
+    pub fn read_dma<'a, RXC>(
+        &'a mut self,
+        buffer: &'a mut [u16],
+        mut rx_channel: RXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit, RXC, RXC, &'a mut [u16]>
+    where
+        RXC: DmaChannel,
+    {
+        self.set_bidi();
+        self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+
+        rx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        rx_channel.set_memory_address(buffer.as_mut_ptr() as u32);
+        rx_channel.set_transfer_length(buffer.len() as u16);
+        rx_channel.start(false, DmaWordSize::HalfWord);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: None,
+            rx_channel: Some(rx_channel),
+            buffer,
+        }
+    }
+
+    /// Exchange `tx`/`rx` over DMA using two channels running concurrently
+    /// with 16-bit frames.
+    ///
+    /// Both channels are armed before either is started so the RX channel
+    /// is always ready to drain the FIFO before the TX side can overflow
+    /// it.
+    pub fn transfer_dma<'a, TXC, RXC>(
+        &'a mut self,
+        tx: &'a [u16],
+        rx: &'a mut [u16],
+        mut tx_channel: TXC,
+        mut rx_channel: RXC,
+    ) -> SpiDmaTransfer<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit, TXC, RXC, (&'a [u16], &'a mut [u16])>
+    where
+        TXC: DmaChannel,
+        RXC: DmaChannel,
+    {
+        assert_eq!(tx.len(), rx.len());
+        self.set_bidi();
+
+        rx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        rx_channel.set_memory_address(rx.as_mut_ptr() as u32);
+        rx_channel.set_transfer_length(rx.len() as u16);
+
+        tx_channel.set_peripheral_address(&self.spi.dr as *const _ as u32);
+        tx_channel.set_memory_address(tx.as_ptr() as u32);
+        tx_channel.set_transfer_length(tx.len() as u16);
+
+        self.spi
+            .cr2
+            .modify(|_, w| w.rxdmaen().set_bit().txdmaen().set_bit());
+
+        // Arm RX before TX so it is always draining the FIFO first.
+        rx_channel.start(false, DmaWordSize::HalfWord);
+        tx_channel.start(true, DmaWordSize::HalfWord);
+
+        SpiDmaTransfer {
+            spi: self,
+            tx_channel: Some(tx_channel),
+            rx_channel: Some(rx_channel),
+            buffer: (tx, rx),
+        }
+    }
+}
+
+/// An `Spi` wrapped for interrupt-driven, `async`-compatible transfers.
+///
+/// Enables `TXEIE`/`RXNEIE`/`ERRIE` so the peripheral raises its interrupt
+/// on every FIFO event instead of requiring the caller to poll. The futures
+/// returned by [`SpiInterrupt::transfer`]/[`SpiInterrupt::write`] register
+/// their waker here; call [`SpiInterrupt::on_interrupt`] from the SPI
+/// interrupt handler to drive them forward.
+pub struct SpiInterrupt<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+    spi: Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>,
+    waker: Cell<Option<Waker>>,
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> SpiInterrupt<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    /// Wrap `spi`, enabling the TX empty, RX not-empty and error interrupts.
+    pub fn new(spi: Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>) -> Self {
+        spi.spi
+            .cr2
+            .modify(|_, w| w.txeie().set_bit().rxneie().set_bit().errie().set_bit());
+
+        SpiInterrupt {
+            spi,
+            waker: Cell::new(None),
+        }
+    }
+
+    /// Call from the SPI interrupt handler to wake any pending future.
+    ///
+    /// The future itself re-checks the status register on wakeup, so this
+    /// just needs to be called whenever the interrupt fires.
+    pub fn on_interrupt(&self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Disable the FIFO interrupts and hand back the underlying `Spi`.
+    pub fn release(self) -> Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+        self.spi
+            .spi
+            .cr2
+            .modify(|_, w| w.txeie().clear_bit().rxneie().clear_bit().errie().clear_bit());
+        self.spi
+    }
+
+    /// Exchange `words` with the bus, returning a future that resolves once
+    /// every word has been sent and its response received.
+    pub fn transfer<'a>(&'a mut self, words: &'a mut [u8]) -> SpiTransferFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+        self.spi.set_bidi();
+        SpiTransferFuture {
+            interrupt: self,
+            words,
+            read_pos: 0,
+            write_pos: 0,
+        }
+    }
+
+    /// Send `words`, returning a future that resolves once the last byte
+    /// has left the TX FIFO.
+    pub fn write<'a>(&'a mut self, words: &'a [u8]) -> SpiWriteFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+        self.spi.set_send_only();
+        SpiWriteFuture {
+            interrupt: self,
+            words,
+            write_pos: 0,
+        }
+    }
+}
+
+/// Check for `OVR`/`MODF`, clearing the fault and disabling the FIFO
+/// interrupts so a real fault fails the in-flight transfer once instead of
+/// re-entering the (level-triggered) SPI interrupt forever.
+fn poll_error<SPI>(spi: &SPI) -> Option<Error>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    let sr = spi.sr.read();
+    let err = if sr.ovr().bit_is_set() {
+        Some(Error::Overrun)
+    } else if sr.modf().bit_is_set() {
+        Some(Error::ModeFault)
+    } else {
+        None
+    };
+
+    if err.is_some() {
+        // OVR clears on a DR-then-SR read sequence; MODF clears on a SR
+        // read followed by a write to CR1 (the cr1 read-modify-write below
+        // covers that).
+        let _ = spi.dr.read();
+        let _ = spi.sr.read();
+        spi.cr1.modify(|_, w| w);
+        spi.cr2
+            .modify(|_, w| w.txeie().clear_bit().rxneie().clear_bit().errie().clear_bit());
+    }
+
+    err
+}
+
+/// Future returned by [`SpiInterrupt::transfer`].
+pub struct SpiTransferFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+    interrupt: &'a SpiInterrupt<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>,
+    words: &'a mut [u8],
+    read_pos: usize,
+    write_pos: usize,
+}
+
+impl<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> Future
+    for SpiTransferFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let spi = &this.interrupt.spi.spi;
+
+        // Register the waker *before* touching the status register: if we
+        // checked status first and registered after, an interrupt landing
+        // in between would be missed (on_interrupt would find no waker
+        // yet) and this future would never be woken again. Registering
+        // first means the recheck below always sees any event that could
+        // have raced with it.
+        this.interrupt.waker.set(Some(cx.waker().clone()));
+
+        if let Some(err) = poll_error(spi) {
+            return Poll::Ready(Err(err));
+        }
+
+        let sr = spi.sr.read();
+        if this.write_pos < this.words.len() && sr.txe().bit_is_set() {
+            let byte = this.words[this.write_pos];
+            unsafe { ptr::write_volatile(spi.dr.as_ptr() as *mut u8, byte) };
+            this.write_pos += 1;
+        }
+        if this.read_pos < this.write_pos && sr.rxne().bit_is_set() {
+            this.words[this.read_pos] =
+                unsafe { ptr::read_volatile(&spi.dr as *const _ as *const u8) };
+            this.read_pos += 1;
+        }
+
+        if this.read_pos == this.words.len() {
+            return Poll::Ready(Ok(()));
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Future returned by [`SpiInterrupt::write`].
+pub struct SpiWriteFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+    interrupt: &'a SpiInterrupt<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>,
+    words: &'a [u8],
+    write_pos: usize,
+}
+
+impl<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> Future for SpiWriteFuture<'a, SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let spi = &this.interrupt.spi.spi;
+
+        // Register the waker *before* touching the status register; see
+        // the comment in `SpiTransferFuture::poll` for why checking first
+        // and registering after would lose wakeups.
+        this.interrupt.waker.set(Some(cx.waker().clone()));
+
+        if let Some(err) = poll_error(spi) {
+            return Poll::Ready(Err(err));
+        }
+
+        if this.write_pos < this.words.len() {
+            if spi.sr.read().txe().bit_is_set() {
+                let byte = this.words[this.write_pos];
+                unsafe { ptr::write_volatile(spi.dr.as_ptr() as *mut u8, byte) };
+                this.write_pos += 1;
+            }
+
+            if this.write_pos < this.words.len() {
+                return Poll::Pending;
+            }
+        }
+
+        if spi.sr.read().bsy().bit_is_set() {
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> ErrorType for Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH> {
+    type Error = Error;
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> SpiBus<u8> for Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        // Clock out dummy bytes while the RX FIFO fills in the response.
+        // Driven word-by-word directly (rather than through `Transfer`,
+        // one call per byte) so a single CRC is computed over the whole
+        // buffer instead of one per byte.
+        self.set_bidi();
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            nb::block!(self.check_send())?;
+            self.send_u8(0);
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
+            nb::block!(self.check_read())?;
+            *word = self.read_u8();
+        }
+        self.check_crc()
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        ::embedded_hal::blocking::spi::Write::write(self, words)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        // Per the `SpiBus` contract, `read`/`write` may differ in length:
+        // the transfer runs for `max(read.len(), write.len())`, zero-padding
+        // the short `write` or discarding the extra bytes read into a short
+        // `read`.
+        self.set_bidi();
+        let len = core::cmp::max(read.len(), write.len());
+        for i in 0..len {
+            nb::block!(self.check_send())?;
+            self.send_u8(write.get(i).copied().unwrap_or(0));
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
+            nb::block!(self.check_read())?;
+            let byte = self.read_u8();
+            if let Some(slot) = read.get_mut(i) {
+                *slot = byte;
+            }
+        }
+        self.check_crc()
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        ::embedded_hal::blocking::spi::Transfer::transfer(self, words)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // Wait for the TX FIFO to drain and the bus to go idle.
+        while self.spi.sr.read().ftlvl().bits() != 0 {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        Ok(())
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN> SpiBus<u16> for Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, SixteenBit>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+{
+    fn read(&mut self, words: &mut [u16]) -> Result<(), Self::Error> {
+        // See the u8 impl above: driven word-by-word so CRC (if enabled)
+        // covers the whole buffer rather than one word per call.
+        self.set_bidi();
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            nb::block!(self.check_send())?;
+            self.send_u16(0);
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
+            nb::block!(self.check_read())?;
+            *word = self.read_u16();
+        }
+        self.check_crc()
+    }
+
+    fn write(&mut self, words: &[u16]) -> Result<(), Self::Error> {
+        ::embedded_hal::blocking::spi::Write::write(self, words)
+    }
+
+    fn transfer(&mut self, read: &mut [u16], write: &[u16]) -> Result<(), Self::Error> {
+        // See the u8 impl above: lengths are allowed to differ.
+        self.set_bidi();
+        let len = core::cmp::max(read.len(), write.len());
+        for i in 0..len {
+            nb::block!(self.check_send())?;
+            self.send_u16(write.get(i).copied().unwrap_or(0));
+            if i + 1 == len {
+                self.assert_crc_next();
+            }
+            nb::block!(self.check_read())?;
+            let word = self.read_u16();
+            if let Some(slot) = read.get_mut(i) {
+                *slot = word;
+            }
+        }
+        self.check_crc()
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u16]) -> Result<(), Self::Error> {
+        ::embedded_hal::blocking::spi::Transfer::transfer(self, words)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while self.spi.sr.read().ftlvl().bits() != 0 {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        Ok(())
+    }
+}
+
+/// Per-transaction configuration applied by [`SpiWithCs`] before asserting
+/// CS: the clock mode, the `BR` prescaler bits, and an optional delay
+/// between words, letting several devices share one bus with different
+/// settings each.
+#[derive(Clone, Copy)]
+pub struct TransferConfig {
+    /// Clock polarity/phase for this transaction.
+    pub mode: Mode,
+    /// Raw `BR` prescaler bits (0b000..=0b111), see `spi_init`.
+    pub br: u8,
+    /// Core clock cycles to wait between words, or 0 to disable.
+    pub inter_word_delay_cycles: u32,
+}
+
+/// An `Spi` paired with a software-managed CS output pin.
+///
+/// Each `transfer`/`write` call reprograms the bus from a [`TransferConfig`],
+/// drives CS low, runs the transaction word-by-word, waits for the bus to
+/// go idle, then drives CS back high. This removes the need to manage CS
+/// by hand, and lets multiple devices share a bus with independent
+/// per-device clock speeds.
+pub struct SpiWithCs<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, CS> {
+    spi: Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>,
+    cs: CS,
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, CS> SpiWithCs<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH, CS>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+    CS: OutputPin,
+{
+    fn apply_config(&mut self, config: &TransferConfig) {
+        self.spi.spi.cr1.modify(|_, w| w.spe().clear_bit());
+        self.spi.spi.cr1.modify(|_, w| {
+            w.cpha()
+                .bit(config.mode.phase == Phase::CaptureOnSecondTransition)
+                .cpol()
+                .bit(config.mode.polarity == Polarity::IdleHigh)
+                .br()
+                .bits(config.br)
+        });
+        self.spi.spi.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Release the CS pin and the underlying `Spi`.
+    pub fn release(self) -> (Spi<SPI, SCKPIN, MISOPIN, MOSIPIN, WIDTH>, CS) {
+        (self.spi, self.cs)
+    }
+}
+
+impl<SPI, SCKPIN, MISOPIN, MOSIPIN, CS> SpiWithCs<SPI, SCKPIN, MISOPIN, MOSIPIN, EightBit, CS>
+where
+    SPI: Deref<Target = SpiRegisterBlock>,
+    CS: OutputPin,
+{
+    /// Run the word-by-word transfer loop itself; CS is managed by the
+    /// callers below so it gets deasserted on every exit path, including a
+    /// bus error partway through.
+    fn run_transfer(&mut self, words: &mut [u8], delay_cycles: u32) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            nb::block!(self.spi.check_send())?;
+            self.spi.send_u8(*word);
+            nb::block!(self.spi.check_read())?;
+            *word = self.spi.read_u8();
+            if delay_cycles > 0 {
+                asm::delay(delay_cycles);
+            }
+        }
+
+        while self.spi.spi.sr.read().bsy().bit_is_set() {}
+        Ok(())
+    }
+
+    /// See [`SpiWithCs::run_transfer`]; sends without reading the RX FIFO.
+    fn run_write(&mut self, words: &[u8], delay_cycles: u32) -> Result<(), Error> {
+        for word in words {
+            nb::block!(self.spi.check_send())?;
+            self.spi.send_u8(*word);
+            if delay_cycles > 0 {
+                asm::delay(delay_cycles);
+            }
+        }
+
+        while self.spi.spi.sr.read().bsy().bit_is_set() {}
+        Ok(())
+    }
+
+    /// Assert CS, exchange `words` bidirectionally, then deassert CS.
+    ///
+    /// CS is always deasserted before returning, even if a bus error (e.g.
+    /// `Overrun`/`ModeFault`) aborts the transaction partway through.
+    pub fn transfer<'w>(
+        &mut self,
+        config: TransferConfig,
+        words: &'w mut [u8],
+    ) -> Result<&'w [u8], Error> {
+        self.apply_config(&config);
+        self.cs.set_low().ok();
+        self.spi.set_bidi();
+
+        let result = self.run_transfer(words, config.inter_word_delay_cycles);
+
+        self.cs.set_high().ok();
+        result.map(|()| &*words)
+    }
+
+    /// Assert CS, send `words`, then deassert CS.
+    ///
+    /// CS is always deasserted before returning, even on a bus error.
+    pub fn write(&mut self, config: TransferConfig, words: &[u8]) -> Result<(), Error> {
+        self.apply_config(&config);
+        self.cs.set_low().ok();
+        self.spi.set_send_only();
+
+        let result = self.run_write(words, config.inter_word_delay_cycles);
+
+        self.cs.set_high().ok();
+        result
+    }
+}